@@ -1,11 +1,11 @@
 //! Validates the MIR to ensure that invariants are upheld.
 
 use super::{MirPass, MirSource};
-use rustc_middle::mir::visit::Visitor;
+use rustc_middle::mir::visit::{PlaceContext, Visitor};
 use rustc_middle::{
     mir::{
-        BasicBlock, Body, Location, Operand, Rvalue, Statement, StatementKind, Terminator,
-        TerminatorKind,
+        tcx::PlaceTy, BasicBlock, Body, Local, Location, MirPhase, Operand, Place, ProjectionElem,
+        Rvalue, Statement, StatementKind, Terminator, TerminatorKind,
     },
     ty::{self, ParamEnv, TyCtxt},
 };
@@ -14,18 +14,23 @@ use rustc_span::def_id::DefId;
 pub struct Validator {
     /// Describes at which point in the pipeline this validation is happening.
     pub when: String,
+    /// The phase the body has already reached. Terminators that this phase or an earlier one
+    /// lowers away are reported if they are still present.
+    pub mir_phase: MirPhase,
 }
 
 impl<'tcx> MirPass<'tcx> for Validator {
     fn run_pass(&self, tcx: TyCtxt<'tcx>, source: MirSource<'tcx>, body: &mut Body<'tcx>) {
         let def_id = source.def_id();
         let param_env = tcx.param_env(def_id);
-        TypeChecker { when: &self.when, def_id, body, tcx, param_env }.visit_body(body);
+        let mir_phase = self.mir_phase;
+        TypeChecker { when: &self.when, mir_phase, def_id, body, tcx, param_env }.visit_body(body);
     }
 }
 
 struct TypeChecker<'a, 'tcx> {
     when: &'a str,
+    mir_phase: MirPhase,
     def_id: DefId,
     body: &'a Body<'tcx>,
     tcx: TyCtxt<'tcx>,
@@ -33,6 +38,32 @@ struct TypeChecker<'a, 'tcx> {
 }
 
 impl<'a, 'tcx> TypeChecker<'a, 'tcx> {
+    fn normalize<T: ty::TypeFoldable<'tcx>>(&self, value: T) -> T {
+        self.tcx.normalize_erasing_regions(self.param_env, value)
+    }
+
+    /// Returns whether the two types are equal enough to be assignment-compatible, after
+    /// normalizing away type aliases and erasing regions.
+    fn mir_assign_valid_types(&self, left: ty::Ty<'tcx>, right: ty::Ty<'tcx>) -> bool {
+        self.normalize(left) == self.normalize(right)
+    }
+
+    /// Returns the width in bits of an integer-like type (`bool` counts as 1 bit, `char` as its
+    /// 32-bit size, integers as their size), or `None` if `ty` is none of those.
+    fn bits_for_switch_ty(&self, ty: ty::Ty<'tcx>) -> Option<u64> {
+        Some(match ty.kind {
+            ty::Bool => 1,
+            ty::Char => 32,
+            ty::Int(int_ty) => {
+                int_ty.bit_width().unwrap_or_else(|| self.tcx.sess.target.ptr_width.into())
+            }
+            ty::Uint(uint_ty) => {
+                uint_ty.bit_width().unwrap_or_else(|| self.tcx.sess.target.ptr_width.into())
+            }
+            _ => return None,
+        })
+    }
+
     fn fail(&self, location: Location, msg: impl AsRef<str>) {
         let span = self.body.source_info(location).span;
         // We use `delay_span_bug` as we might see broken MIR when other errors have already
@@ -49,14 +80,167 @@ impl<'a, 'tcx> TypeChecker<'a, 'tcx> {
         );
     }
 
-    fn check_bb(&self, location: Location, bb: BasicBlock) {
+    /// Check a jump from the current location to `bb`. `is_cleanup_edge` indicates whether this
+    /// is the unwind/cleanup edge of the terminator, as opposed to one of its normal successors.
+    fn check_bb(&self, location: Location, bb: BasicBlock, is_cleanup_edge: bool) {
         if self.body.basic_blocks().get(bb).is_none() {
-            self.fail(location, format!("encountered jump to invalid basic block {:?}", bb))
+            self.fail(location, format!("encountered jump to invalid basic block {:?}", bb));
+            return;
+        }
+
+        let src_is_cleanup = self.body.basic_blocks()[location.block].is_cleanup;
+        let target_is_cleanup = self.body.basic_blocks()[bb].is_cleanup;
+
+        if is_cleanup_edge && !target_is_cleanup {
+            self.fail(
+                location,
+                format!("encountered unwind edge to non-cleanup basic block {:?}", bb),
+            );
+        }
+        if !is_cleanup_edge && !src_is_cleanup && target_is_cleanup {
+            self.fail(
+                location,
+                format!(
+                    "encountered non-cleanup edge from non-cleanup block into cleanup basic block {:?}",
+                    bb
+                ),
+            );
+        }
+        if !is_cleanup_edge && src_is_cleanup && !target_is_cleanup {
+            self.fail(
+                location,
+                format!(
+                    "encountered normal (non-unwind) edge out of cleanup basic block {:?} into non-cleanup basic block {:?}",
+                    location.block, bb
+                ),
+            );
         }
     }
 }
 
 impl<'a, 'tcx> Visitor<'tcx> for TypeChecker<'a, 'tcx> {
+    fn visit_local(&mut self, local: &Local, _context: PlaceContext, location: Location) {
+        if self.body.local_decls.get(*local).is_none() {
+            self.fail(
+                location,
+                format!("encountered dangling local {:?} that does not exist in `local_decls`", local),
+            );
+        }
+    }
+
+    fn visit_place(&mut self, place: &Place<'tcx>, context: PlaceContext, location: Location) {
+        // The local itself must exist before we can look up its declared type below — `fail`
+        // does not abort compilation, so we have to bail out of the projection walk by hand
+        // rather than let a dangling local turn into an out-of-bounds index.
+        if self.body.local_decls.get(place.local).is_none() {
+            self.fail(
+                location,
+                format!(
+                    "encountered dangling local {:?} that does not exist in `local_decls`",
+                    place.local
+                ),
+            );
+            return;
+        }
+
+        // Walk the projection, checking that every element is valid for the type it is applied
+        // to, so that passes which rewrite places can't leave dangling locals or out-of-bounds
+        // field/variant accesses behind.
+        let mut place_ty = PlaceTy::from_ty(self.body.local_decls[place.local].ty);
+
+        for elem in place.projection.iter() {
+            match elem {
+                ProjectionElem::Deref => {
+                    if place_ty.ty.builtin_deref(true).is_none() {
+                        self.fail(
+                            location,
+                            format!(
+                                "encountered `Deref` projection on non-dereferenceable type {}",
+                                place_ty.ty
+                            ),
+                        );
+                        // `projection_ty` assumes a `Deref` is only ever applied to a
+                        // dereferenceable type; don't fold it over the type we just rejected.
+                        break;
+                    }
+                }
+                ProjectionElem::Field(field, _) => {
+                    let fields = match place_ty.ty.kind {
+                        ty::Tuple(substs) => Some(substs.len()),
+                        ty::Adt(adt_def, _) => {
+                            let variant = match place_ty.variant_index {
+                                Some(index) => &adt_def.variants[index],
+                                None => adt_def.non_enum_variant(),
+                            };
+                            Some(variant.fields.len())
+                        }
+                        ty::Closure(_, substs) => Some(substs.as_closure().upvar_tys().count()),
+                        ty::Generator(_, substs, _) => {
+                            Some(substs.as_generator().upvar_tys().count())
+                        }
+                        _ => None,
+                    };
+                    match fields {
+                        Some(fields) if field.as_usize() < fields => {}
+                        Some(fields) => {
+                            self.fail(
+                                location,
+                                format!(
+                                    "encountered out-of-bounds field {:?} of type {} (which has {} fields)",
+                                    field, place_ty.ty, fields
+                                ),
+                            );
+                            // The field index is bad, so there is no sound type to keep
+                            // projecting into; stop here instead of folding over it below.
+                            break;
+                        }
+                        None => {
+                            self.fail(
+                                location,
+                                format!(
+                                    "encountered `Field` projection on non-field-having type {}",
+                                    place_ty.ty
+                                ),
+                            );
+                            break;
+                        }
+                    }
+                }
+                ProjectionElem::Downcast(_, index) => match place_ty.ty.kind {
+                    ty::Adt(adt_def, _) if adt_def.is_enum() => {
+                        if index.as_usize() >= adt_def.variants.len() {
+                            self.fail(
+                                location,
+                                format!(
+                                    "encountered `Downcast` to out-of-range variant {:?} of {}",
+                                    index, place_ty.ty
+                                ),
+                            );
+                            // A later `Field` projection would index `adt_def.variants` with
+                            // this same out-of-range index via `place_ty.variant_index`.
+                            break;
+                        }
+                    }
+                    _ => {
+                        self.fail(
+                            location,
+                            format!(
+                                "encountered `Downcast` projection on non-enum type {}",
+                                place_ty.ty
+                            ),
+                        );
+                        break;
+                    }
+                },
+                _ => {}
+            }
+
+            place_ty = place_ty.projection_ty(self.tcx, elem);
+        }
+
+        self.super_place(place, context, location);
+    }
+
     fn visit_operand(&mut self, operand: &Operand<'tcx>, location: Location) {
         // `Operand::Copy` is only supposed to be used with `Copy` types.
         if let Operand::Copy(place) = operand {
@@ -72,9 +256,9 @@ impl<'a, 'tcx> Visitor<'tcx> for TypeChecker<'a, 'tcx> {
     }
 
     fn visit_statement(&mut self, statement: &Statement<'tcx>, location: Location) {
-        // The sides of an assignment must not alias. Currently this just checks whether the places
-        // are identical.
         if let StatementKind::Assign(box (dest, rvalue)) = &statement.kind {
+            // The sides of an assignment must not alias. Currently this just checks whether the
+            // places are identical.
             match rvalue {
                 Rvalue::Use(Operand::Copy(src) | Operand::Move(src)) => {
                     if dest == src {
@@ -86,15 +270,68 @@ impl<'a, 'tcx> Visitor<'tcx> for TypeChecker<'a, 'tcx> {
                 }
                 _ => {}
             }
+
+            // The type of the left-hand side `Place` must match the type of the right-hand side
+            // `Rvalue`, or the MIR is malformed.
+            let left_ty = dest.ty(&self.body.local_decls, self.tcx).ty;
+            let right_ty = rvalue.ty(&self.body.local_decls, self.tcx);
+            if !self.mir_assign_valid_types(left_ty, right_ty) {
+                self.fail(
+                    location,
+                    format!(
+                        "encountered `{:?}` with incompatible types:\nleft-hand side has type: {}\nright-hand side has type: {}",
+                        statement.kind, left_ty, right_ty,
+                    ),
+                );
+            }
         }
     }
 
     fn visit_terminator(&mut self, terminator: &Terminator<'tcx>, location: Location) {
+        // Check that this terminator kind has already been lowered away if the current phase
+        // demands it, so that later passes can rely on it being absent.
+        match &terminator.kind {
+            TerminatorKind::Drop { .. } | TerminatorKind::DropAndReplace { .. }
+                if self.mir_phase >= MirPhase::DropLowering =>
+            {
+                self.fail(
+                    location,
+                    format!(
+                        "encountered `{:?}` that should have been lowered away by this point",
+                        terminator.kind
+                    ),
+                );
+            }
+            TerminatorKind::Yield { .. } | TerminatorKind::GeneratorDrop
+                if self.mir_phase >= MirPhase::GeneratorLowering =>
+            {
+                self.fail(
+                    location,
+                    format!(
+                        "encountered `{:?}` that should have been lowered away by this point",
+                        terminator.kind
+                    ),
+                );
+            }
+            TerminatorKind::FalseEdge { .. } | TerminatorKind::FalseUnwind { .. }
+                if self.mir_phase >= MirPhase::Optimized =>
+            {
+                self.fail(
+                    location,
+                    format!(
+                        "encountered `{:?}` that should have been lowered away by this point",
+                        terminator.kind
+                    ),
+                );
+            }
+            _ => {}
+        }
+
         match &terminator.kind {
             TerminatorKind::Goto { target } => {
-                self.check_bb(location, *target);
+                self.check_bb(location, *target, false);
             }
-            TerminatorKind::SwitchInt { targets, values, .. } => {
+            TerminatorKind::SwitchInt { targets, values, discr, .. } => {
                 if targets.len() != values.len() + 1 {
                     self.fail(
                         location,
@@ -105,20 +342,60 @@ impl<'a, 'tcx> Visitor<'tcx> for TypeChecker<'a, 'tcx> {
                         ),
                     );
                 }
+
+                let discr_ty = discr.ty(&self.body.local_decls, self.tcx);
+                match self.bits_for_switch_ty(discr_ty) {
+                    None => self.fail(
+                        location,
+                        format!(
+                            "encountered `SwitchInt` terminator with non-integer-like discriminant type {}",
+                            discr_ty
+                        ),
+                    ),
+                    Some(bits) => {
+                        if discr_ty == self.tcx.types.bool {
+                            for value in values {
+                                if *value != 0 && *value != 1 {
+                                    self.fail(
+                                        location,
+                                        format!(
+                                            "encountered `SwitchInt` terminator on `bool` with out-of-range value {}",
+                                            value
+                                        ),
+                                    );
+                                }
+                            }
+                        } else if bits < 128 {
+                            let max = (1u128 << bits) - 1;
+                            for value in values {
+                                if *value > max {
+                                    self.fail(
+                                        location,
+                                        format!(
+                                            "encountered `SwitchInt` terminator with value {} that does not fit in {} ({} bits)",
+                                            value, discr_ty, bits
+                                        ),
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+
                 for target in targets {
-                    self.check_bb(location, *target);
+                    self.check_bb(location, *target, false);
                 }
             }
             TerminatorKind::Drop { target, unwind, .. } => {
-                self.check_bb(location, *target);
+                self.check_bb(location, *target, false);
                 if let Some(unwind) = unwind {
-                    self.check_bb(location, *unwind);
+                    self.check_bb(location, *unwind, true);
                 }
             }
             TerminatorKind::DropAndReplace { target, unwind, .. } => {
-                self.check_bb(location, *target);
+                self.check_bb(location, *target, false);
                 if let Some(unwind) = unwind {
-                    self.check_bb(location, *unwind);
+                    self.check_bb(location, *unwind, true);
                 }
             }
             TerminatorKind::Call { func, destination, cleanup, .. } => {
@@ -131,10 +408,10 @@ impl<'a, 'tcx> Visitor<'tcx> for TypeChecker<'a, 'tcx> {
                     ),
                 }
                 if let Some((_, target)) = destination {
-                    self.check_bb(location, *target);
+                    self.check_bb(location, *target, false);
                 }
                 if let Some(cleanup) = cleanup {
-                    self.check_bb(location, *cleanup);
+                    self.check_bb(location, *cleanup, true);
                 }
             }
             TerminatorKind::Assert { cond, target, cleanup, .. } => {
@@ -148,30 +425,30 @@ impl<'a, 'tcx> Visitor<'tcx> for TypeChecker<'a, 'tcx> {
                         ),
                     );
                 }
-                self.check_bb(location, *target);
+                self.check_bb(location, *target, false);
                 if let Some(cleanup) = cleanup {
-                    self.check_bb(location, *cleanup);
+                    self.check_bb(location, *cleanup, true);
                 }
             }
             TerminatorKind::Yield { resume, drop, .. } => {
-                self.check_bb(location, *resume);
+                self.check_bb(location, *resume, false);
                 if let Some(drop) = drop {
-                    self.check_bb(location, *drop);
+                    self.check_bb(location, *drop, false);
                 }
             }
             TerminatorKind::FalseEdge { real_target, imaginary_target } => {
-                self.check_bb(location, *real_target);
-                self.check_bb(location, *imaginary_target);
+                self.check_bb(location, *real_target, false);
+                self.check_bb(location, *imaginary_target, false);
             }
             TerminatorKind::FalseUnwind { real_target, unwind } => {
-                self.check_bb(location, *real_target);
+                self.check_bb(location, *real_target, false);
                 if let Some(unwind) = unwind {
-                    self.check_bb(location, *unwind);
+                    self.check_bb(location, *unwind, true);
                 }
             }
             TerminatorKind::InlineAsm { destination, .. } => {
                 if let Some(destination) = destination {
-                    self.check_bb(location, *destination);
+                    self.check_bb(location, *destination, false);
                 }
             }
             // Nothing to validate for these.
@@ -0,0 +1,32 @@
+// check-pass
+// compile-flags: -Z validate-mir
+
+// Regression test for the `mir_assign_valid_types` check added to
+// `TypeChecker::visit_statement`: every one of these assignments is well-typed, and all of them
+// must keep passing MIR validation. In particular this exercises the rvalue kinds that are
+// trickiest to get right here, since their `.ty()` is computed rather than just read off a
+// place: `Aggregate` (tuple and closure upvars), `Ref` (of a field projection), and
+// `Discriminant` (of an enum with an explicit repr).
+
+#[repr(u8)]
+enum E {
+    A,
+    B(i32),
+}
+
+fn exercise(x: i32, y: i32, e: &E) -> i32 {
+    let a: i32 = x; // Use
+    let b: i32 = x + y; // CheckedBinaryOp (overflow checks are on in this unoptimized build)
+    let c: i64 = x as i64; // Cast
+    let s: (i32, i32) = (x, y); // Aggregate (tuple)
+    let r: &i32 = &s.0; // Ref of a field projection
+    let d: u8 = match *e { E::A => 0, E::B(_) => 1 }; // Discriminant (read by the match)
+    let f = move || x + y; // Aggregate (closure upvars)
+
+    let _ = d;
+    a + b + c as i32 + *r + s.1 + f()
+}
+
+fn main() {
+    exercise(1, 2, &E::B(3));
+}
@@ -0,0 +1,14 @@
+// compile-flags: -Z validate-mir
+
+// Negative counterpart to `assign-rvalue-ty-match.rs`: a place whose declared type does not match
+// the type of the value assigned to it. HIR type-checking already rejects this before MIR is
+// ever built, so it never reaches `TypeChecker::visit_statement` in practice — genuinely
+// malformed MIR of this shape is only ever produced by a buggy optimization pass, which can't be
+// expressed as valid surface syntax. This test instead pins down that the surrounding type error
+// is still reported the normal way (rather than, say, an internal compiler error) when `-Z
+// validate-mir` is on, so the validator's `delay_span_bug` never surfaces for code that already
+// failed to type-check for an unrelated reason.
+
+fn main() {
+    let x: i32 = "not an i32"; //~ ERROR mismatched types
+}